@@ -0,0 +1,255 @@
+//! Decoding of the per-codec identification and comment packets that make up
+//! the start of every Ogg logical bitstream, so the TUI can show something
+//! more useful than raw header bytes.
+
+use std::convert::TryInto;
+
+use crate::BareOggFormat;
+
+/// Decoded fields from a stream's identification packet (the very first
+/// packet of a logical bitstream), one variant per codec we understand.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodecInfo {
+    Vorbis(VorbisIdent),
+    Opus(OpusHead),
+    Theora(TheoraIdent),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VorbisIdent {
+    pub vorbis_version: u32,
+    pub audio_channels: u8,
+    pub audio_sample_rate: u32,
+    pub bitrate_maximum: u32,
+    pub bitrate_nominal: u32,
+    pub bitrate_minimum: u32,
+}
+
+/// See https://tools.ietf.org/html/rfc7845#section-5.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpusHead {
+    pub version: u8,
+    pub channel_count: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+    pub output_gain: i16,
+    pub channel_mapping_family: u8,
+}
+
+/// Theora's identification header is a bit-packed bitstream in the real
+/// spec; this is a byte-aligned approximation that is good enough for
+/// display purposes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TheoraIdent {
+    pub frame_width_mb: u16,
+    pub frame_height_mb: u16,
+    pub fps_numerator: u32,
+    pub fps_denominator: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VorbisComments {
+    pub vendor: String,
+    pub comments: Vec<(String, String)>,
+}
+
+/// Parses the identification packet of a logical bitstream, given the magic
+/// offset returned by `identify_packet_data_by_magic`.
+pub fn parse_codec_info(
+    format: BareOggFormat,
+    pck_data: &[u8],
+    header_len: usize,
+) -> Option<CodecInfo> {
+    match format {
+        BareOggFormat::Vorbis => parse_vorbis_ident(pck_data, header_len).map(CodecInfo::Vorbis),
+        BareOggFormat::Opus => parse_opus_head(pck_data, header_len).map(CodecInfo::Opus),
+        BareOggFormat::Theora => parse_theora_ident(pck_data, header_len).map(CodecInfo::Theora),
+        BareOggFormat::Speex
+        | BareOggFormat::Skeleton
+        | BareOggFormat::Flac
+        | BareOggFormat::Pcm
+        | BareOggFormat::Dirac
+        | BareOggFormat::Kate
+        | BareOggFormat::Vp8 => None,
+    }
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(u16::from_le_bytes(bytes))
+}
+
+fn read_i16_le(data: &[u8], offset: usize) -> Option<i16> {
+    let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(i16::from_le_bytes(bytes))
+}
+
+fn parse_vorbis_ident(pck_data: &[u8], header_len: usize) -> Option<VorbisIdent> {
+    let vorbis_version = read_u32_le(pck_data, header_len)?;
+    let audio_channels = *pck_data.get(header_len + 4)?;
+    let audio_sample_rate = read_u32_le(pck_data, header_len + 5)?;
+    let bitrate_maximum = read_u32_le(pck_data, header_len + 9)?;
+    let bitrate_nominal = read_u32_le(pck_data, header_len + 13)?;
+    let bitrate_minimum = read_u32_le(pck_data, header_len + 17)?;
+
+    Some(VorbisIdent {
+        vorbis_version,
+        audio_channels,
+        audio_sample_rate,
+        bitrate_maximum,
+        bitrate_nominal,
+        bitrate_minimum,
+    })
+}
+
+fn parse_opus_head(pck_data: &[u8], header_len: usize) -> Option<OpusHead> {
+    let version = *pck_data.get(header_len)?;
+    let channel_count = *pck_data.get(header_len + 1)?;
+    let pre_skip = read_u16_le(pck_data, header_len + 2)?;
+    let input_sample_rate = read_u32_le(pck_data, header_len + 4)?;
+    let output_gain = read_i16_le(pck_data, header_len + 8)?;
+    let channel_mapping_family = *pck_data.get(header_len + 10)?;
+
+    Some(OpusHead {
+        version,
+        channel_count,
+        pre_skip,
+        input_sample_rate,
+        output_gain,
+        channel_mapping_family,
+    })
+}
+
+fn parse_theora_ident(pck_data: &[u8], header_len: usize) -> Option<TheoraIdent> {
+    // 3 version bytes (vmaj, vmin, vrev), then width/height in macroblocks.
+    let frame_width_mb = read_u16_le(pck_data, header_len + 3)?;
+    let frame_height_mb = read_u16_le(pck_data, header_len + 5)?;
+    // Skip the picture region width/height/offset fields (8 bytes).
+    let fps_numerator = read_u32_le(pck_data, header_len + 15)?;
+    let fps_denominator = read_u32_le(pck_data, header_len + 19)?;
+
+    Some(TheoraIdent {
+        frame_width_mb,
+        frame_height_mb,
+        fps_numerator,
+        fps_denominator,
+    })
+}
+
+/// The Theora setup header (third packet of the stream) carries the
+/// `KFGSHIFT` value needed to split a granule position into a keyframe
+/// number and an offset. Like `parse_theora_ident`, this is a byte-aligned
+/// approximation of a header that is really bit-packed.
+pub fn parse_theora_granule_shift(setup_packet: &[u8]) -> Option<u8> {
+    const THEORA_SETUP_MAGIC_LEN: usize = 7; // 0x82 + "theora"
+    setup_packet.get(THEORA_SETUP_MAGIC_LEN).copied()
+}
+
+/// Speex's identification header is a fixed, byte-aligned C struct; the
+/// sample rate sits right after the magic, version string and two int32
+/// version fields (8 + 20 + 4 + 4 bytes).
+///
+/// See http://www.speex.org/docs/manual/speex-manual/node8.html
+pub fn speex_sample_rate(pck_data: &[u8]) -> Option<u32> {
+    const SPEEX_RATE_OFFSET: usize = 8 + 20 + 4 + 4;
+    read_u32_le(pck_data, SPEEX_RATE_OFFSET)
+}
+
+/// Decodes a page's granule position into a playback time, given the format
+/// and decoded identification-header state of its owning stream. Falls back
+/// to `None` (raw hex only) when the format or the fields it needs aren't
+/// known yet.
+pub fn granule_seconds(
+    format: Option<BareOggFormat>,
+    codec_info: Option<&CodecInfo>,
+    granule_shift: Option<u8>,
+    speex_sample_rate: Option<u32>,
+    granule: u64,
+) -> Option<f64> {
+    match codec_info {
+        Some(CodecInfo::Vorbis(ident)) => {
+            if ident.audio_sample_rate == 0 {
+                return None;
+            }
+            Some(granule as f64 / ident.audio_sample_rate as f64)
+        }
+        Some(CodecInfo::Opus(head)) => {
+            let adjusted = granule.checked_sub(head.pre_skip as u64)?;
+            Some(adjusted as f64 / 48_000.0)
+        }
+        Some(CodecInfo::Theora(ident)) => {
+            let shift = granule_shift?;
+            // Real KFGSHIFT is 0-31 (it splits a 32-bit-ish granule field);
+            // `parse_theora_granule_shift` just reads a raw byte from the
+            // setup packet, so malformed input can hand us anything up to
+            // 255. Shifting a u64 by >= 64 panics in debug builds.
+            if shift >= 32 {
+                return None;
+            }
+            if ident.fps_numerator == 0 {
+                return None;
+            }
+            let mask = (1u64 << shift) - 1;
+            let frame_index = (granule >> shift) + (granule & mask);
+            Some(frame_index as f64 * ident.fps_denominator as f64 / ident.fps_numerator as f64)
+        }
+        None if format == Some(BareOggFormat::Speex) => {
+            let rate = speex_sample_rate?;
+            if rate == 0 {
+                return None;
+            }
+            Some(granule as f64 / rate as f64)
+        }
+        None => None,
+    }
+}
+
+/// Length of the comment-packet magic for codecs that embed Vorbis-comment
+/// style tags, so callers can skip straight to the vendor string.
+fn comment_header_len(format: BareOggFormat) -> Option<usize> {
+    match format {
+        BareOggFormat::Vorbis => Some(7),  // 0x03 + "vorbis"
+        BareOggFormat::Theora => Some(7),  // 0x81 + "theora"
+        BareOggFormat::Opus => Some(8),    // "OpusTags"
+        BareOggFormat::Speex
+        | BareOggFormat::Skeleton
+        | BareOggFormat::Flac
+        | BareOggFormat::Pcm
+        | BareOggFormat::Dirac
+        | BareOggFormat::Kate
+        | BareOggFormat::Vp8 => None,
+    }
+}
+
+/// Parses the comment packet (the second packet of a logical bitstream) into
+/// a vendor string and a list of `KEY=value` tags.
+pub fn parse_comments(format: BareOggFormat, pck_data: &[u8]) -> Option<VorbisComments> {
+    let header_len = comment_header_len(format)?;
+
+    let vendor_length = read_u32_le(pck_data, header_len)? as usize;
+    let vendor_start = header_len + 4;
+    let vendor_end = vendor_start + vendor_length;
+    let vendor = String::from_utf8_lossy(pck_data.get(vendor_start..vendor_end)?).into_owned();
+
+    let comment_count = read_u32_le(pck_data, vendor_end)? as usize;
+    let mut offset = vendor_end + 4;
+    let mut comments = Vec::with_capacity(comment_count);
+    for _ in 0..comment_count {
+        let entry_length = read_u32_le(pck_data, offset)? as usize;
+        offset += 4;
+        let entry = String::from_utf8_lossy(pck_data.get(offset..offset + entry_length)?);
+        offset += entry_length;
+
+        let mut parts = entry.splitn(2, '=');
+        let key = parts.next()?.to_string();
+        let value = parts.next().unwrap_or("").to_string();
+        comments.push((key, value));
+    }
+
+    Some(VorbisComments { vendor, comments })
+}
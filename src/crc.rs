@@ -0,0 +1,38 @@
+//! Ogg page checksums: CRC-32 with generator polynomial `0x04C11DB7`, an
+//! initial value of zero, and no input/output reflection or final XOR.
+//!
+//! See https://www.xiph.org/ogg/doc/framing.html
+
+use std::sync::OnceLock;
+
+const POLY: u32 = 0x04C1_1DB7;
+
+/// Built once and cached: `checksum` runs per-page on every render frame, and
+/// rebuilding the table each time would be O(pages × 256) work per frame.
+fn table() -> &'static [u32; 256] {
+    static TABLE: OnceLock<[u32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = (i as u32) << 24;
+            for _ in 0..8 {
+                crc = if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ POLY
+                } else {
+                    crc << 1
+                };
+            }
+            *entry = crc;
+        }
+        table
+    })
+}
+
+pub fn checksum(data: &[u8]) -> u32 {
+    let table = table();
+    let mut crc: u32 = 0;
+    for &byte in data {
+        crc = (crc << 8) ^ table[(((crc >> 24) ^ byte as u32) & 0xff) as usize];
+    }
+    crc
+}
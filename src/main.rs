@@ -1,4 +1,4 @@
-use ogg::reading::{PacketReader, PageParser};
+use ogg::reading::PageParser;
 use ogg::writing::PacketWriteEndInfo;
 use ogg::writing::PacketWriter;
 use rustc_hex::ToHex;
@@ -19,11 +19,18 @@ use tui::style::{Color, Modifier, Style};
 use tui::widgets::{Block, Borders, List, Paragraph, Text};
 use tui::Terminal;
 
+use crate::codec_info::{
+    granule_seconds, parse_codec_info, parse_comments, parse_theora_granule_shift,
+    speex_sample_rate, CodecInfo, VorbisComments,
+};
 use crate::util::{
     event::{Event, Events},
     StatefulList,
 };
 
+mod codec_info;
+mod crc;
+mod mp4;
 mod util;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -33,53 +40,183 @@ enum BareOggFormat {
     Theora,
     Speex,
     Skeleton,
+    Flac,
+    Pcm,
+    Dirac,
+    Kate,
+    Vp8,
+}
+
+/// One entry in the content-detection registry: a magic byte sequence that
+/// identifies a codec's first packet, the format it maps to, and how many
+/// bytes of that magic to skip to reach the codec-specific fields after it.
+/// `score` lets a detector outrank others on ambiguous input; when left
+/// `None` it defaults to `header_len`, so if two detectors both match (e.g.
+/// one's magic is a prefix of another's), the one with the longer magic
+/// wins. Only a genuine tie (equal score) falls back to registration order.
+/// No two magics in the registry are prefixes of each other today, so at
+/// most one detector ever matches a given input — give a new detector an
+/// explicit `score` if that stops being true and longest-match isn't what
+/// you want.
+struct Detector {
+    magic: &'static [u8],
+    format: BareOggFormat,
+    header_len: usize,
+    score: Option<fn(&[u8]) -> usize>,
 }
 
+/// Registry of known Ogg codec mappings, in the style of a layered
+/// demuxer-registry: add a new codec by adding a new entry here.
+///
 /// See https://github.com/est31/ogg-metadata/blob/b61e5f28530b5d461b98cb0167e8a561af436ebd/src/lib.rs#L154
+fn detectors() -> &'static [Detector] {
+    use BareOggFormat::*;
+    &[
+        // https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-620004.2.1
+        Detector { magic: b"\x01vorbis", format: Vorbis, header_len: 7, score: None },
+        // https://tools.ietf.org/html/rfc7845#section-5.1
+        Detector { magic: b"OpusHead", format: Opus, header_len: 8, score: None },
+        // https://www.theora.org/doc/Theora.pdf#section.6.2
+        Detector { magic: b"\x80theora", format: Theora, header_len: 7, score: None },
+        // http://www.speex.org/docs/manual/speex-manual/node8.html
+        Detector { magic: b"Speex   ", format: Speex, header_len: 8, score: None },
+        // https://wiki.xiph.org/Ogg_Skeleton_4#Ogg_Skeleton_version_4.0_Format_Specification
+        Detector { magic: b"fishead\0", format: Skeleton, header_len: 8, score: None },
+        // https://xiph.org/flac/ogg_mapping.html
+        Detector { magic: b"\x7fFLAC", format: Flac, header_len: 5, score: None },
+        // https://wiki.xiph.org/OggPCM
+        Detector { magic: b"PCM     ", format: Pcm, header_len: 8, score: None },
+        // https://wiki.xiph.org/OggDirac
+        Detector { magic: b"BBCD", format: Dirac, header_len: 4, score: None },
+        // https://wiki.xiph.org/OggKate
+        Detector { magic: b"\x80kate\0\0\0", format: Kate, header_len: 8, score: None },
+        // https://wiki.xiph.org/OggVP8
+        Detector { magic: b"OVP80", format: Vp8, header_len: 5, score: None },
+    ]
+}
+
+/// Runs the detector registry against the first packet of a logical
+/// bitstream and returns the best-matching format with the number of
+/// leading magic bytes to skip to reach its codec-specific fields.
 fn identify_packet_data_by_magic(pck_data: &[u8]) -> Option<(usize, BareOggFormat)> {
-    // Magic sequences.
-    // https://www.xiph.org/vorbis/doc/Vorbis_I_spec.html#x1-620004.2.1
-    let vorbis_magic = &[0x01, 0x76, 0x6f, 0x72, 0x62, 0x69, 0x73];
-    // https://tools.ietf.org/html/rfc7845#section-5.1
-    let opus_magic = &[0x4f, 0x70, 0x75, 0x73, 0x48, 0x65, 0x61, 0x64];
-    // https://www.theora.org/doc/Theora.pdf#section.6.2
-    let theora_magic = &[0x80, 0x74, 0x68, 0x65, 0x6f, 0x72, 0x61];
-    // http://www.speex.org/docs/manual/speex-manual/node8.html
-    let speex_magic = &[0x53, 0x70, 0x65, 0x65, 0x78, 0x20, 0x20, 0x20];
-    // https://wiki.xiph.org/Ogg_Skeleton_4#Ogg_Skeleton_version_4.0_Format_Specification
-    let skeleton_magic = &[0x66, 105, 115, 104, 101, 97, 100, 0];
-
-    if pck_data.len() < 1 {
-        return None;
+    let mut best: Option<(&Detector, usize)> = None;
+    for detector in detectors() {
+        if !pck_data.starts_with(detector.magic) {
+            continue;
+        }
+        let score = detector
+            .score
+            .map(|score_fn| score_fn(pck_data))
+            .unwrap_or(detector.header_len);
+
+        // Strictly greater, not greater-or-equal: on a tied score the
+        // earlier (already chosen) detector keeps it. Scores usually differ
+        // (they default to header_len, so longer magics outrank shorter
+        // ones they're a prefix of); this only matters for genuine ties.
+        if best.map_or(true, |(_, best_score)| score > best_score) {
+            best = Some((detector, score));
+        }
     }
 
-    use BareOggFormat::*;
-    let ret: (usize, BareOggFormat) = match pck_data[0] {
-        0x01 if pck_data.starts_with(vorbis_magic) => (vorbis_magic.len(), Vorbis),
-        0x4f if pck_data.starts_with(opus_magic) => (opus_magic.len(), Opus),
-        0x80 if pck_data.starts_with(theora_magic) => (theora_magic.len(), Theora),
-        0x53 if pck_data.starts_with(speex_magic) => (speex_magic.len(), Speex),
-        0x66 if pck_data.starts_with(skeleton_magic) => (speex_magic.len(), Skeleton),
-
-        _ => return None,
-    };
+    best.map(|(detector, _)| (detector.header_len, detector.format))
+}
 
-    return Some(ret);
+/// `MediaInfo`-style summary of a single logical bitstream: the codec it was
+/// identified as, its decoded identification header, and any tags found in
+/// its comment header.
+struct StreamMediaInfo {
+    format: Option<BareOggFormat>,
+    codec_info: Option<CodecInfo>,
+    comments: Option<VorbisComments>,
+    granule_shift: Option<u8>,
+    speex_sample_rate: Option<u32>,
 }
 
-fn select_bitstream_with_video(
-    bitstreams: HashMap<u32, Vec<ogg::Packet>>,
-) -> Option<Vec<ogg::Packet>> {
-    let mut select_bitstream = None;
-    for (_, bitstream) in bitstreams {
-        // let mut packet_reader = BufReader::new(std::io::Cursor::new(&bitstream[0].data));
-        let format = identify_packet_data_by_magic(&bitstream[0].data);
-        match format {
-            Some((_, BareOggFormat::Theora)) => select_bitstream = Some(bitstream),
-            _ => {}
-        }
+impl StreamMediaInfo {
+    /// Converts a page's raw granule position into a playback time, if this
+    /// stream's identification header told us enough to do so.
+    fn granule_seconds(&self, granule: u64) -> Option<f64> {
+        granule_seconds(
+            self.format,
+            self.codec_info.as_ref(),
+            self.granule_shift,
+            self.speex_sample_rate,
+            granule,
+        )
+    }
+
+    fn display_text(&self) -> String {
+        let format = match self.format {
+            Some(format) => format!("{:?}", format),
+            None => "Unknown".to_string(),
+        };
+
+        let codec_info = match &self.codec_info {
+            Some(codec_info) => format!("{:#?}", codec_info),
+            None => "(identification header not decoded)".to_string(),
+        };
+
+        let comments = match &self.comments {
+            Some(comments) => {
+                let tags: Vec<String> = comments
+                    .comments
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                format!("vendor: {}\n{}", comments.vendor, tags.join("\n"))
+            }
+            None => "(no comment header)".to_string(),
+        };
+
+        format!(
+            "Format: {}\n\n{}\n\nTags:\n{}",
+            format, codec_info, comments
+        )
     }
-    select_bitstream
+}
+
+fn build_stream_info(bitstreams: &HashMap<u32, Vec<Packet>>) -> HashMap<u32, StreamMediaInfo> {
+    bitstreams
+        .iter()
+        .map(|(serial, packets)| {
+            let ident_packet = packets.get(0);
+            let magic_match =
+                ident_packet.and_then(|packet| identify_packet_data_by_magic(&packet.data));
+
+            let format = magic_match.map(|(_, format)| format);
+            let codec_info = match (magic_match, ident_packet) {
+                (Some((header_len, format)), Some(packet)) => {
+                    parse_codec_info(format, &packet.data, header_len)
+                }
+                _ => None,
+            };
+            let comments = match (format, packets.get(1)) {
+                (Some(format), Some(packet)) => parse_comments(format, &packet.data),
+                _ => None,
+            };
+            let granule_shift = match (format, packets.get(2)) {
+                (Some(BareOggFormat::Theora), Some(setup_packet)) => {
+                    parse_theora_granule_shift(&setup_packet.data)
+                }
+                _ => None,
+            };
+            let speex_rate = match (format, ident_packet) {
+                (Some(BareOggFormat::Speex), Some(packet)) => speex_sample_rate(&packet.data),
+                _ => None,
+            };
+
+            (
+                *serial,
+                StreamMediaInfo {
+                    format,
+                    codec_info,
+                    comments,
+                    granule_shift,
+                    speex_sample_rate: speex_rate,
+                },
+            )
+        })
+        .collect()
 }
 
 struct PageHeader {
@@ -101,7 +238,12 @@ impl PageHeader {
         PageParser::new(self.bytes)
     }
 
-    pub fn byte_display_text(&self) -> Vec<Text> {
+    pub fn byte_display_text(&self, stream_info: Option<&StreamMediaInfo>) -> Vec<Text> {
+        let granule_time = stream_info
+            .and_then(|info| info.granule_seconds(self.granule_position_parsed()))
+            .map(format_duration)
+            .unwrap_or_else(|| "unknown".to_string());
+
         vec![
             Text::styled(
                 self.capture_pattern().to_hex::<String>(),
@@ -130,6 +272,9 @@ impl PageHeader {
                 self.granule_position()[6..8].to_hex::<String>(),
                 Style::default().fg(Color::White).bg(Color::Green),
             ),
+            Text::raw("\n"),
+            Text::raw(format!("({})", granule_time)),
+            Text::raw("\n"),
             Text::styled(
                 self.bitstream_serial_number()[0..2].to_hex::<String>(),
                 Style::default().fg(Color::White).bg(Color::Blue),
@@ -176,6 +321,14 @@ impl PageHeader {
         u32::from_le_bytes(int_bytes.try_into().unwrap())
     }
 
+    pub fn bitstream_serial_number_parsed(&self) -> u32 {
+        u32::from_le_bytes(self.bitstream_serial_number().try_into().unwrap())
+    }
+
+    pub fn granule_position_parsed(&self) -> u64 {
+        u64::from_le_bytes(self.granule_position().try_into().unwrap())
+    }
+
     pub fn capture_pattern(&self) -> &[u8] {
         &self.bytes[0..4]
     }
@@ -204,118 +357,343 @@ impl PageHeader {
         &self.bytes[22..26]
     }
 
+    pub fn checksum_parsed(&self) -> u32 {
+        u32::from_le_bytes(self.checksum().try_into().unwrap())
+    }
+
     pub fn page_segments(&self) -> &[u8] {
         &self.bytes[26..27]
     }
 }
 
-fn get_packets() -> Vec<PageHeader> {
-    let in_file_path = std::env::args()
+/// A fully parsed Ogg page: the 27-byte header, its segment (lacing) table,
+/// and the page body the lacing values carve into packets.
+struct Page {
+    header: PageHeader,
+    segments: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl Page {
+    fn is_continuation(&self) -> bool {
+        self.header.header_type()[0] & 0x01 != 0
+    }
+
+    fn is_bos(&self) -> bool {
+        self.header.header_type()[0] & 0x02 != 0
+    }
+
+    fn is_eos(&self) -> bool {
+        self.header.header_type()[0] & 0x04 != 0
+    }
+
+    /// Describes the packets carved out of this page's lacing table, for
+    /// display alongside the raw header bytes.
+    fn packets_text(&self) -> String {
+        let mut lines = Vec::new();
+        if self.is_bos() {
+            lines.push("[BOS]".to_string());
+        }
+        if self.is_continuation() {
+            lines.push("(starts with continuation)".to_string());
+        }
+
+        let mut packet_len = 0usize;
+        let mut packet_index = 0usize;
+        for &lacing_value in &self.segments {
+            packet_len += lacing_value as usize;
+            if lacing_value < 255 {
+                lines.push(format!("packet {}: {} bytes", packet_index, packet_len));
+                packet_index += 1;
+                packet_len = 0;
+            }
+        }
+        if packet_len > 0 {
+            lines.push(format!(
+                "packet {} continues on next page: {} bytes so far",
+                packet_index, packet_len
+            ));
+        }
+
+        if self.is_eos() {
+            lines.push("[EOS]".to_string());
+        }
+
+        lines.join("\n")
+    }
+
+    /// The full on-disk bytes of the page: header, lacing table and body.
+    fn raw_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(27 + self.segments.len() + self.body.len());
+        bytes.extend_from_slice(&self.header.bytes);
+        bytes.extend_from_slice(&self.segments);
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    /// Recomputes this page's CRC-32 (with the stored checksum bytes zeroed
+    /// out, as the checksum itself excludes them) and compares it against
+    /// the checksum recorded in the header.
+    fn checksum_valid(&self) -> bool {
+        let mut bytes = self.raw_bytes();
+        for byte in &mut bytes[22..26] {
+            *byte = 0;
+        }
+        crc::checksum(&bytes) == self.header.checksum_parsed()
+    }
+}
+
+/// A fully reconstructed Ogg packet, stitched together from one or more
+/// pages' lacing segments per the Ogg framing rules.
+struct Packet {
+    stream_serial: u32,
+    data: Vec<u8>,
+    eos: bool,
+    /// Granule position of the page this packet completed on.
+    granule_position: u64,
+}
+
+/// Reads every page's lacing table and concatenates consecutive segments
+/// into packets, honoring the continuation bit to stitch packets that span
+/// page boundaries, and groups the result by logical bitstream.
+fn reconstruct_packets(pages: &[Page]) -> HashMap<u32, Vec<Packet>> {
+    let mut bitstreams: HashMap<u32, Vec<Packet>> = HashMap::new();
+    let mut pending: HashMap<u32, Vec<u8>> = HashMap::new();
+
+    for page in pages {
+        let serial = page.header.bitstream_serial_number_parsed();
+        let mut packet_data = if page.is_continuation() {
+            pending.remove(&serial).unwrap_or_default()
+        } else {
+            pending.remove(&serial);
+            Vec::new()
+        };
+
+        let mut offset = 0usize;
+        for (i, &lacing_value) in page.segments.iter().enumerate() {
+            let segment = &page.body[offset..offset + lacing_value as usize];
+            packet_data.extend_from_slice(segment);
+            offset += lacing_value as usize;
+
+            if lacing_value < 255 {
+                let is_last_segment = i == page.segments.len() - 1;
+                let packet = Packet {
+                    stream_serial: serial,
+                    eos: page.is_eos() && is_last_segment,
+                    data: std::mem::take(&mut packet_data),
+                    granule_position: page.header.granule_position_parsed(),
+                };
+                bitstreams.entry(serial).or_insert_with(Vec::new).push(packet);
+            }
+        }
+
+        if !packet_data.is_empty() {
+            pending.insert(serial, packet_data);
+        }
+    }
+
+    bitstreams
+}
+
+/// Formats a duration in seconds as `HH:MM:SS.mmm`.
+fn format_duration(seconds: f64) -> String {
+    let total_millis = (seconds * 1000.0).round() as i64;
+    let millis = total_millis.rem_euclid(1000);
+    let total_seconds = total_millis.div_euclid(1000);
+    let secs = total_seconds.rem_euclid(60);
+    let total_minutes = total_seconds.div_euclid(60);
+    let mins = total_minutes.rem_euclid(60);
+    let hours = total_minutes.div_euclid(60);
+
+    format!("{:02}:{:02}:{:02}.{:03}", hours, mins, secs, millis)
+}
+
+/// Re-muxes the reconstructed packets of every logical bitstream through
+/// `PacketWriter`, which gives the output file freshly computed page
+/// checksums and, since truncated trailing pages were already dropped by
+/// `read_pages`, drops any data that didn't make it into a whole page.
+///
+/// Streams are written whole, one after another (in first-seen order, since
+/// `reconstruct_packets` groups them by serial and loses the original
+/// interleaving) rather than interleaved as in the source file. Each
+/// packet's granule position is also only as precise as `reconstruct_packets`
+/// gives us: every packet completing on the same page shares that page's
+/// granule, so only the last packet per page gets an accurate value.
+fn repair_file(in_file_path: &str, pages: &[Page]) -> io::Result<String> {
+    let mut bitstreams = reconstruct_packets(pages);
+
+    let mut serial_order = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for page in pages {
+        let serial = page.header.bitstream_serial_number_parsed();
+        if seen.insert(serial) {
+            serial_order.push(serial);
+        }
+    }
+
+    let out_file_path = format!("{}.repaired.ogg", in_file_path);
+    let out_file = File::create(&out_file_path)?;
+    let mut writer = PacketWriter::new(BufWriter::new(out_file));
+
+    for serial in serial_order {
+        let packets = match bitstreams.remove(&serial) {
+            Some(packets) => packets,
+            None => continue,
+        };
+        for packet in packets {
+            let end_info = if packet.eos {
+                PacketWriteEndInfo::EndStream
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            writer.write_packet(packet.data, serial, end_info, packet.granule_position)?;
+        }
+    }
+
+    Ok(out_file_path)
+}
+
+fn input_file_path() -> String {
+    std::env::args()
         .nth(1)
-        .expect("Missing input video argument");
+        .expect("Missing input video argument")
+}
+
+/// Reads a single page starting at `start`, or `None` if the file ends
+/// before a full page (header, lacing table and body) could be read.
+fn read_page_at(in_video: &mut File, start: u64) -> Option<Page> {
+    in_video.seek(SeekFrom::Start(start)).ok()?;
+
+    let mut header_bytes: [u8; 27] = [0; 27];
+    in_video.read_exact(&mut header_bytes).ok()?;
+    let header = PageHeader {
+        bytes: header_bytes,
+    };
+
+    let mut segments = vec![0u8; header.page_segments_count() as usize];
+    in_video.read_exact(&mut segments).ok()?;
+
+    let body_len: u64 = segments.iter().map(|&value| value as u64).sum();
+    let mut body = vec![0u8; body_len as usize];
+    in_video.read_exact(&mut body).ok()?;
+
+    Some(Page {
+        header,
+        segments,
+        body,
+    })
+}
 
-    let mut in_video_meta = std::fs::metadata(in_file_path.clone()).unwrap();
+/// Reads every page in the file, keeping the segment table and body so
+/// packets can be reconstructed per the Ogg lacing rules. A trailing page
+/// truncated by a cut-off file is silently dropped rather than returned
+/// half-read.
+fn read_pages(in_file_path: &str) -> Vec<Page> {
+    let in_video_meta = std::fs::metadata(in_file_path).unwrap();
     let mut in_video = File::open(in_file_path).unwrap();
 
-    // dbg!(ogg_metadata::read_format(&mut in_video));
-    // dbg!(ogg_metadata::read_format(&mut in_video));
-    // dbg!(ogg_metadata::read_format(&mut in_video));
-    // dbg!(ogg_metadata::read_format(in_audio));
-
-    // let mut in_video_logical_bitstreams = HashMap::new();
-    // let mut in_video_reader = PacketReader::new(BufReader::new(in_video));
-    // while let Some(packet) = in_video_reader.read_packet().unwrap() {
-    // in_video_logical_bitstreams
-    // .entry(packet.stream_serial())
-    // .or_insert(Vec::new())
-    // .push(packet);
-    // }
-
-    let mut next_header_start = 0;
-    let mut headers = Vec::new();
-    while in_video_meta.len() != next_header_start {
-        let mut header_bytes: [u8; 27] = [0; 27];
-        in_video.seek(SeekFrom::Start(next_header_start)).unwrap();
-        in_video.read_exact(&mut header_bytes).unwrap();
-        let header = PageHeader {
-            bytes: header_bytes,
+    let mut next_page_start = 0;
+    let mut pages = Vec::new();
+    while next_page_start < in_video_meta.len() {
+        let page = match read_page_at(&mut in_video, next_page_start) {
+            Some(page) => page,
+            None => break,
         };
-        let segments_lengths: u64 = (0..header.page_segments_count())
-            .into_iter()
-            .map(|_| [0u8; 1])
-            .map(|mut segment_length| {
-                in_video.read_exact(&mut segment_length).unwrap();
-                segment_length
-            })
-            .map(|segment_length| segment_length[0] as u64)
-            .sum();
-
-        next_header_start += 27u64 + header.page_segments_count() as u64 + segments_lengths;
-        headers.push(header);
-    }
-
-    headers
-
-    // let second_header_start =
-    // 27u64 + first_header.page_segments_count() as u64 + first_segments_lengths;
-    // dbg!(second_header_start);
-    // let mut second_header_bytes: [u8; 27] = [0; 27];
-    // in_video.seek(SeekFrom::Start(second_header_start)).unwrap();
-    // in_video.read_exact(&mut second_header_bytes).unwrap();
-    // let second_header = PageHeader {
-    // bytes: second_header_bytes,
-    // };
-    // let second_segments_lengths: u64 = (0..second_header.page_segments_count())
-    // .into_iter()
-    // .map(|_| [0u8; 1])
-    // .map(|mut segment_length| {
-    // in_video.read_exact(&mut segment_length).unwrap();
-    // segment_length
-    // })
-    // .map(|segment_length| segment_length[0] as u64)
-    // .sum();
-
-    // let third_header_start = 27u64
-    // + first_header.page_segments_count() as u64
-    // + first_segments_lengths
-    // + 27u64
-    // + second_header.page_segments_count() as u64
-    // + second_segments_lengths;
-    // let mut third_header_bytes: [u8; 27] = [0; 27];
-    // in_video.seek(SeekFrom::Start(third_header_start)).unwrap();
-    // in_video.read_exact(&mut third_header_bytes).unwrap();
-    // let third_header = PageHeader {
-    // bytes: third_header_bytes,
-    // };
-    // let third_segments_lengths: u64 = (0..third_header.page_segments_count())
-    // .into_iter()
-    // .map(|_| [0u8; 1])
-    // .map(|mut segment_length| {
-    // in_video.read_exact(&mut segment_length).unwrap();
-    // segment_length
-    // })
-    // .map(|segment_length| segment_length[0] as u64)
-    // .sum();
-
-    // // dbg!(segments_lengths);
-
-    // vec![first_header, second_header, third_header]
+
+        next_page_start += 27u64 + page.segments.len() as u64 + page.body.len() as u64;
+        pages.push(page);
+    }
+
+    pages
 }
 
 struct App {
-    page_headers: StatefulList<PageHeader>,
+    in_file_path: String,
+    pages: StatefulList<Page>,
+    stream_info: HashMap<u32, StreamMediaInfo>,
+    bitstreams: HashMap<u32, Vec<Packet>>,
+    status: Option<String>,
 }
 
 impl App {
-    fn new(page_headers: Vec<PageHeader>) -> App {
+    fn new(
+        in_file_path: String,
+        pages: Vec<Page>,
+        stream_info: HashMap<u32, StreamMediaInfo>,
+        bitstreams: HashMap<u32, Vec<Packet>>,
+    ) -> App {
         App {
-            page_headers: StatefulList::with_items(page_headers),
+            in_file_path,
+            pages: StatefulList::with_items(pages),
+            stream_info,
+            bitstreams,
+            status: None,
         }
     }
+
+    fn repair(&mut self) {
+        self.status = Some(match repair_file(&self.in_file_path, &self.pages.items) {
+            Ok(out_file_path) => format!("repaired -> {}", out_file_path),
+            Err(err) => format!("repair failed: {}", err),
+        });
+    }
+
+    /// Exports the logical bitstream of the currently selected page to a
+    /// fragmented MP4 (CMAF) file next to the input file.
+    ///
+    /// There's no automatic "pick the video stream" step here: the user
+    /// picks a page (and with it a bitstream) from the list themselves, and
+    /// `mp4::export_fragmented_mp4` only has a sample entry for Opus/Vorbis
+    /// anyway, so a Theora auto-selector would have nothing useful to feed
+    /// it yet. Revisit this once Theora export is supported.
+    fn export_selected(&mut self) {
+        let selected_serial = self
+            .pages
+            .state
+            .selected()
+            .and_then(|item_index| self.pages.items.get(item_index))
+            .map(|page| page.header.bitstream_serial_number_parsed());
+
+        self.status = Some(match selected_serial {
+            Some(serial) => match self.export_bitstream(serial) {
+                Ok(out_file_path) => format!("exported -> {}", out_file_path),
+                Err(err) => format!("export failed: {}", err),
+            },
+            None => "export failed: no page selected".to_string(),
+        });
+    }
+
+    fn export_bitstream(&self, serial: u32) -> io::Result<String> {
+        let info = self.stream_info.get(&serial);
+        let format = info.and_then(|info| info.format);
+        let codec_info = info.and_then(|info| info.codec_info.as_ref());
+        let packets = self.bitstreams.get(&serial);
+
+        let mp4_bytes = match (format, codec_info, packets) {
+            (Some(format), Some(codec_info), Some(packets)) => {
+                mp4::export_fragmented_mp4(format, codec_info, packets)
+            }
+            _ => None,
+        };
+        let mp4_bytes = mp4_bytes.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "unsupported or undecoded stream for MP4 export",
+            )
+        })?;
+
+        let out_file_path = format!("{}.{:x}.fmp4.mp4", self.in_file_path, serial);
+        let mut out_file = File::create(&out_file_path)?;
+        out_file.write_all(&mp4_bytes)?;
+        Ok(out_file_path)
+    }
 }
 
 fn main() -> Result<(), failure::Error> {
-    let page_headers = get_packets();
+    let in_file_path = input_file_path();
+    let pages = read_pages(&in_file_path);
+    let bitstreams = reconstruct_packets(&pages);
+    let stream_info = build_stream_info(&bitstreams);
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode()?;
     let stdout = MouseTerminal::from(stdout);
@@ -327,43 +705,77 @@ fn main() -> Result<(), failure::Error> {
     let events = Events::new();
 
     // App
-    let mut app = App::new(page_headers);
+    let mut app = App::new(in_file_path, pages, stream_info, bitstreams);
 
     loop {
         terminal.draw(|mut f| {
             let lr_chunks = Layout::default()
                 .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .constraints(
+                    [
+                        Constraint::Percentage(34),
+                        Constraint::Percentage(33),
+                        Constraint::Percentage(33),
+                    ]
+                    .as_ref(),
+                )
                 .split(f.size());
 
             let style = Style::default().fg(Color::White).bg(Color::Black);
 
-            let items = app
-                .page_headers
-                .items
-                .iter()
-                .map(|i| Text::raw(i.display_text()));
+            let items = app.pages.items.iter().map(|page| {
+                if page.checksum_valid() {
+                    Text::raw(page.header.display_text())
+                } else {
+                    Text::styled(
+                        page.header.display_text(),
+                        Style::default().fg(Color::White).bg(Color::Red),
+                    )
+                }
+            });
+            let list_title = match &app.status {
+                Some(status) => format!("List ({})", status),
+                None => "List ('r' to repair, 'e' to export MP4)".to_string(),
+            };
             let items = List::new(items)
-                .block(Block::default().borders(Borders::ALL).title("List"))
+                .block(Block::default().borders(Borders::ALL).title(&list_title))
                 .style(style)
                 .highlight_style(style.fg(Color::LightGreen).modifier(Modifier::BOLD))
                 .highlight_symbol(">");
-            f.render_stateful_widget(items, lr_chunks[0], &mut app.page_headers.state);
+            f.render_stateful_widget(items, lr_chunks[0], &mut app.pages.state);
 
-            let selected_page_header = {
-                app.page_headers
+            let selected_page = {
+                app.pages
                     .state
                     .selected()
-                    .map(|item_index| app.page_headers.items.get(item_index).unwrap())
+                    .map(|item_index| app.pages.items.get(item_index).unwrap())
             };
-            if let Some(selected_page_header) = selected_page_header {
-                let text = selected_page_header.byte_display_text();
+            if let Some(selected_page) = selected_page {
+                let stream_info = app
+                    .stream_info
+                    .get(&selected_page.header.bitstream_serial_number_parsed());
+
+                let mut text = selected_page.header.byte_display_text(stream_info);
+                text.push(Text::raw("\n\n"));
+                text.push(Text::raw(selected_page.packets_text()));
                 let byte_display = Paragraph::new(text.iter())
                     .block(Block::default().title("Header bytes").borders(Borders::ALL))
                     .style(Style::default().fg(Color::White).bg(Color::Black))
                     .alignment(Alignment::Center)
                     .wrap(true);
                 f.render_widget(byte_display, lr_chunks[1]);
+
+                let info_text = vec![Text::raw(
+                    stream_info
+                        .map(|info| info.display_text())
+                        .unwrap_or_else(|| "(no stream info)".to_string()),
+                )];
+                let info_display = Paragraph::new(info_text.iter())
+                    .block(Block::default().title("Stream info").borders(Borders::ALL))
+                    .style(Style::default().fg(Color::White).bg(Color::Black))
+                    .alignment(Alignment::Left)
+                    .wrap(true);
+                f.render_widget(info_display, lr_chunks[2]);
             }
 
             // let events = app.events.iter().map(|&(evt, level)| {
@@ -388,14 +800,20 @@ fn main() -> Result<(), failure::Error> {
                 Key::Char('q') => {
                     break;
                 }
+                Key::Char('r') => {
+                    app.repair();
+                }
+                Key::Char('e') => {
+                    app.export_selected();
+                }
                 Key::Left => {
-                    app.page_headers.unselect();
+                    app.pages.unselect();
                 }
                 Key::Down => {
-                    app.page_headers.next();
+                    app.pages.next();
                 }
                 Key::Up => {
-                    app.page_headers.previous();
+                    app.pages.previous();
                 }
                 _ => {}
             },
@@ -0,0 +1,443 @@
+//! A small ISO-BMFF (fragmented MP4 / CMAF) box writer, just enough to
+//! re-package a reconstructed Ogg logical bitstream's packets as samples
+//! without re-encoding them.
+
+use crate::codec_info::{CodecInfo, OpusHead};
+use crate::{BareOggFormat, Packet};
+
+/// Writes a box: a 4-byte size placeholder, the fourcc, then whatever `f`
+/// appends, with the placeholder backpatched to the box's final size.
+fn write_box<F: FnOnce(&mut Vec<u8>)>(out: &mut Vec<u8>, fourcc: &[u8; 4], f: F) {
+    let size_pos = out.len();
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(fourcc);
+    f(out);
+    let size = (out.len() - size_pos) as u32;
+    out[size_pos..size_pos + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Like `write_box`, but also writes the `(version << 24) | flags` word
+/// that "full boxes" (most boxes with version/flags semantics) start with.
+fn write_full_box<F: FnOnce(&mut Vec<u8>)>(
+    out: &mut Vec<u8>,
+    fourcc: &[u8; 4],
+    version: u8,
+    flags: u32,
+    f: F,
+) {
+    write_box(out, fourcc, |out| {
+        let version_and_flags = ((version as u32) << 24) | (flags & 0x00FF_FFFF);
+        out.extend_from_slice(&version_and_flags.to_be_bytes());
+        f(out);
+    });
+}
+
+fn write_ftyp(out: &mut Vec<u8>) {
+    write_box(out, b"ftyp", |out| {
+        out.extend_from_slice(b"iso5"); // major brand
+        out.extend_from_slice(&0u32.to_be_bytes()); // minor version
+        out.extend_from_slice(b"iso5");
+        out.extend_from_slice(b"iso6");
+        out.extend_from_slice(b"mp41");
+    });
+}
+
+fn write_mvhd(out: &mut Vec<u8>, timescale: u32, duration: u32) {
+    write_full_box(out, b"mvhd", 0, 0, |out| {
+        out.extend_from_slice(&0u32.to_be_bytes()); // creation_time
+        out.extend_from_slice(&0u32.to_be_bytes()); // modification_time
+        out.extend_from_slice(&timescale.to_be_bytes());
+        out.extend_from_slice(&duration.to_be_bytes());
+        out.extend_from_slice(&0x0001_0000u32.to_be_bytes()); // rate, 1.0
+        out.extend_from_slice(&0x0100u16.to_be_bytes()); // volume, 1.0
+        out.extend_from_slice(&[0u8; 2]); // reserved
+        out.extend_from_slice(&[0u8; 8]); // reserved
+        // unity transformation matrix
+        for value in &[
+            0x0001_0000i32,
+            0,
+            0,
+            0,
+            0x0001_0000,
+            0,
+            0,
+            0,
+            0x4000_0000,
+        ] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        out.extend_from_slice(&[0u8; 24]); // pre_defined
+        out.extend_from_slice(&2u32.to_be_bytes()); // next_track_ID
+    });
+}
+
+fn write_tkhd(out: &mut Vec<u8>, track_id: u32, duration: u32) {
+    write_full_box(out, b"tkhd", 0, 0x7, |out| {
+        out.extend_from_slice(&0u32.to_be_bytes()); // creation_time
+        out.extend_from_slice(&0u32.to_be_bytes()); // modification_time
+        out.extend_from_slice(&track_id.to_be_bytes());
+        out.extend_from_slice(&[0u8; 4]); // reserved
+        out.extend_from_slice(&duration.to_be_bytes());
+        out.extend_from_slice(&[0u8; 8]); // reserved
+        out.extend_from_slice(&0u16.to_be_bytes()); // layer
+        out.extend_from_slice(&0u16.to_be_bytes()); // alternate_group
+        out.extend_from_slice(&0x0100u16.to_be_bytes()); // volume, 1.0 (audio track)
+        out.extend_from_slice(&[0u8; 2]); // reserved
+        for value in &[
+            0x0001_0000i32,
+            0,
+            0,
+            0,
+            0x0001_0000,
+            0,
+            0,
+            0,
+            0x4000_0000,
+        ] {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        out.extend_from_slice(&0u32.to_be_bytes()); // width (audio track, fixed-point 16.16)
+        out.extend_from_slice(&0u32.to_be_bytes()); // height
+    });
+}
+
+fn write_mdhd(out: &mut Vec<u8>, timescale: u32, duration: u32) {
+    write_full_box(out, b"mdhd", 0, 0, |out| {
+        out.extend_from_slice(&0u32.to_be_bytes()); // creation_time
+        out.extend_from_slice(&0u32.to_be_bytes()); // modification_time
+        out.extend_from_slice(&timescale.to_be_bytes());
+        out.extend_from_slice(&duration.to_be_bytes());
+        out.extend_from_slice(&0x55C4u16.to_be_bytes()); // language: "und"
+        out.extend_from_slice(&0u16.to_be_bytes()); // pre_defined
+    });
+}
+
+fn write_hdlr(out: &mut Vec<u8>) {
+    write_full_box(out, b"hdlr", 0, 0, |out| {
+        out.extend_from_slice(&[0u8; 4]); // pre_defined
+        out.extend_from_slice(b"soun");
+        out.extend_from_slice(&[0u8; 12]); // reserved
+        out.extend_from_slice(b"ogg-explorer\0");
+    });
+}
+
+fn write_smhd(out: &mut Vec<u8>) {
+    write_full_box(out, b"smhd", 0, 0, |out| {
+        out.extend_from_slice(&0u16.to_be_bytes()); // balance
+        out.extend_from_slice(&[0u8; 2]); // reserved
+    });
+}
+
+fn write_dinf(out: &mut Vec<u8>) {
+    write_box(out, b"dinf", |out| {
+        write_full_box(out, b"dref", 0, 0, |out| {
+            out.extend_from_slice(&1u32.to_be_bytes()); // entry_count
+            write_full_box(out, b"url ", 0, 0x1, |_| {}); // self-contained
+        });
+    });
+}
+
+fn write_audio_sample_entry<F: FnOnce(&mut Vec<u8>)>(
+    out: &mut Vec<u8>,
+    fourcc: &[u8; 4],
+    channel_count: u16,
+    sample_rate: u32,
+    f: F,
+) {
+    write_box(out, fourcc, |out| {
+        out.extend_from_slice(&[0u8; 6]); // reserved
+        out.extend_from_slice(&1u16.to_be_bytes()); // data_reference_index
+        out.extend_from_slice(&[0u8; 8]); // reserved
+        out.extend_from_slice(&channel_count.to_be_bytes());
+        out.extend_from_slice(&16u16.to_be_bytes()); // samplesize
+        out.extend_from_slice(&[0u8; 4]); // pre_defined + reserved
+        out.extend_from_slice(&(sample_rate << 16).to_be_bytes()); // 16.16 fixed point
+        f(out);
+    });
+}
+
+fn write_dops(out: &mut Vec<u8>, head: &OpusHead) {
+    write_box(out, b"dOps", |out| {
+        out.push(0); // version
+        out.push(head.channel_count);
+        out.extend_from_slice(&head.pre_skip.to_be_bytes());
+        out.extend_from_slice(&head.input_sample_rate.to_be_bytes());
+        out.extend_from_slice(&head.output_gain.to_be_bytes());
+        out.push(head.channel_mapping_family);
+    });
+}
+
+/// There's no standardized Vorbis-in-ISOBMFF sample entry; this stuffs the
+/// raw length-prefixed Vorbis identification/comment/setup packets into a
+/// private configuration box so at least the codec config travels with the
+/// file, mirroring how some existing muxers smuggle Vorbis into MP4.
+fn write_vorbis_config(out: &mut Vec<u8>, headers: &[&[u8]]) {
+    write_box(out, b"vCfg", |out| {
+        out.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        for header in headers {
+            out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+            out.extend_from_slice(header);
+        }
+    });
+}
+
+fn write_stbl(
+    out: &mut Vec<u8>,
+    format: BareOggFormat,
+    codec_info: &CodecInfo,
+    vorbis_headers: &[&[u8]],
+) {
+    write_box(out, b"stbl", |out| {
+        write_full_box(out, b"stsd", 0, 0, |out| {
+            out.extend_from_slice(&1u32.to_be_bytes()); // entry_count
+
+            match (format, codec_info) {
+                (BareOggFormat::Opus, CodecInfo::Opus(head)) => {
+                    write_audio_sample_entry(
+                        out,
+                        b"Opus",
+                        head.channel_count as u16,
+                        head.input_sample_rate,
+                        |out| write_dops(out, head),
+                    );
+                }
+                (BareOggFormat::Vorbis, CodecInfo::Vorbis(ident)) => {
+                    write_audio_sample_entry(
+                        out,
+                        b"mp4a",
+                        ident.audio_channels as u16,
+                        ident.audio_sample_rate,
+                        |out| write_vorbis_config(out, vorbis_headers),
+                    );
+                }
+                _ => {}
+            }
+        });
+        write_full_box(out, b"stts", 0, 0, |out| {
+            out.extend_from_slice(&0u32.to_be_bytes()); // entry_count, fragmented
+        });
+        write_full_box(out, b"stsc", 0, 0, |out| {
+            out.extend_from_slice(&0u32.to_be_bytes());
+        });
+        write_full_box(out, b"stsz", 0, 0, |out| {
+            out.extend_from_slice(&0u32.to_be_bytes()); // sample_size
+            out.extend_from_slice(&0u32.to_be_bytes()); // sample_count
+        });
+        write_full_box(out, b"stco", 0, 0, |out| {
+            out.extend_from_slice(&0u32.to_be_bytes());
+        });
+    });
+}
+
+fn write_trak(
+    out: &mut Vec<u8>,
+    track_id: u32,
+    timescale: u32,
+    duration: u32,
+    format: BareOggFormat,
+    codec_info: &CodecInfo,
+    vorbis_headers: &[&[u8]],
+) {
+    write_box(out, b"trak", |out| {
+        write_tkhd(out, track_id, duration);
+        write_box(out, b"mdia", |out| {
+            write_mdhd(out, timescale, duration);
+            write_hdlr(out);
+            write_box(out, b"minf", |out| {
+                write_smhd(out);
+                write_dinf(out);
+                write_stbl(out, format, codec_info, vorbis_headers);
+            });
+        });
+    });
+}
+
+fn write_mvex(out: &mut Vec<u8>, track_id: u32) {
+    write_box(out, b"mvex", |out| {
+        write_full_box(out, b"trex", 0, 0, |out| {
+            out.extend_from_slice(&track_id.to_be_bytes());
+            out.extend_from_slice(&1u32.to_be_bytes()); // default_sample_description_index
+            out.extend_from_slice(&0u32.to_be_bytes()); // default_sample_duration
+            out.extend_from_slice(&0u32.to_be_bytes()); // default_sample_size
+            out.extend_from_slice(&0u32.to_be_bytes()); // default_sample_flags
+        });
+    });
+}
+
+fn write_moov(
+    out: &mut Vec<u8>,
+    track_id: u32,
+    timescale: u32,
+    duration: u32,
+    format: BareOggFormat,
+    codec_info: &CodecInfo,
+    vorbis_headers: &[&[u8]],
+) {
+    write_box(out, b"moov", |out| {
+        write_mvhd(out, timescale, duration);
+        write_trak(out, track_id, timescale, duration, format, codec_info, vorbis_headers);
+        write_mvex(out, track_id);
+    });
+}
+
+/// Duration of a single Opus packet, in 48 kHz samples, decoded from its TOC
+/// byte per RFC 6716 section 3.1. Returns `None` for code-3 ("arbitrary frame
+/// count") packets, whose packing is expensive to decode for display purposes
+/// only; callers should fall back to a coarser estimate in that case.
+fn opus_packet_duration_samples(data: &[u8]) -> Option<u32> {
+    let toc = *data.first()?;
+    let config = toc >> 3;
+    let frame_count_code = toc & 0x3;
+
+    let base_samples: u32 = if config < 12 {
+        [480, 960, 1920, 2880][(config % 4) as usize]
+    } else if config < 16 {
+        [480, 960][(config % 2) as usize]
+    } else {
+        [120, 240, 480, 960][(config % 4) as usize]
+    };
+
+    let frame_count = match frame_count_code {
+        0 => 1,
+        1 | 2 => 2,
+        _ => return None,
+    };
+
+    Some(base_samples * frame_count)
+}
+
+/// One `moof` + `mdat` pair carrying every sample. Opus sample durations are
+/// decoded from each packet's TOC byte, which is exact except for code-3
+/// packets, which use the granule fallback below. Other codecs have no
+/// per-packet duration available from Ogg framing alone (a page's granule
+/// position only tells you where the *last* packet completing on it ends),
+/// so their durations fall back to the delta from each packet's (page)
+/// granule position to the next packet's; packets sharing a page therefore
+/// get a duration of 0, the last packet on a page gets the gap up to the
+/// next page's granule position, and the final packet repeats the delta
+/// from the packet before it.
+fn write_fragment(
+    out: &mut Vec<u8>,
+    track_id: u32,
+    sequence_number: u32,
+    format: BareOggFormat,
+    packets: &[Packet],
+) {
+    let granule_delta = |i: usize| -> u32 {
+        let duration = if i + 1 < packets.len() {
+            packets[i + 1]
+                .granule_position
+                .saturating_sub(packets[i].granule_position)
+        } else if i > 0 {
+            packets[i]
+                .granule_position
+                .saturating_sub(packets[i - 1].granule_position)
+        } else {
+            0
+        };
+        duration as u32
+    };
+
+    let mut durations = Vec::with_capacity(packets.len());
+    for (i, packet) in packets.iter().enumerate() {
+        let duration = if format == BareOggFormat::Opus {
+            opus_packet_duration_samples(&packet.data).unwrap_or_else(|| granule_delta(i))
+        } else {
+            granule_delta(i)
+        };
+        durations.push(duration);
+    }
+
+    let moof_start = out.len();
+    write_box(out, b"moof", |out| {
+        write_full_box(out, b"mfhd", 0, 0, |out| {
+            out.extend_from_slice(&sequence_number.to_be_bytes());
+        });
+        write_box(out, b"traf", |out| {
+            write_full_box(out, b"tfhd", 0, 0x0002_0000, |out| {
+                out.extend_from_slice(&track_id.to_be_bytes());
+            });
+            write_full_box(out, b"tfdt", 0, 0, |out| {
+                out.extend_from_slice(&0u32.to_be_bytes()); // baseMediaDecodeTime
+            });
+
+            let trun_flags = 0x0000_0001 | 0x0000_0100 | 0x0000_0200; // data-offset, duration, size
+            write_full_box(out, b"trun", 0, trun_flags, |out| {
+                out.extend_from_slice(&(packets.len() as u32).to_be_bytes());
+                out.extend_from_slice(&0i32.to_be_bytes()); // data_offset, backpatched below
+                for (packet, duration) in packets.iter().zip(&durations) {
+                    out.extend_from_slice(&duration.to_be_bytes());
+                    out.extend_from_slice(&(packet.data.len() as u32).to_be_bytes());
+                }
+            });
+        });
+    });
+    let moof_len = out.len() - moof_start;
+
+    // `trun`'s data_offset is counted from the start of the `moof` box to
+    // the first byte of sample data in the following `mdat`.
+    let data_offset = (moof_len + 8) as i32;
+    let data_offset_pos = out.len() - 8 * packets.len() - 4;
+    out[data_offset_pos..data_offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+
+    write_box(out, b"mdat", |out| {
+        for packet in packets {
+            out.extend_from_slice(&packet.data);
+        }
+    });
+}
+
+/// Repackages a logical bitstream's reconstructed packets into a
+/// fragmented MP4 (CMAF) file without re-encoding the payloads. Returns
+/// `None` for formats that don't have a supported MP4 sample entry.
+pub fn export_fragmented_mp4(
+    format: BareOggFormat,
+    codec_info: &CodecInfo,
+    packets: &[Packet],
+) -> Option<Vec<u8>> {
+    if packets.is_empty() {
+        return None;
+    }
+    if !matches!(format, BareOggFormat::Opus | BareOggFormat::Vorbis) {
+        return None;
+    }
+
+    let timescale = match codec_info {
+        CodecInfo::Opus(_) => 48_000,
+        CodecInfo::Vorbis(ident) => ident.audio_sample_rate,
+        CodecInfo::Theora(_) => return None,
+    };
+    let duration = packets
+        .last()
+        .unwrap()
+        .granule_position
+        .saturating_sub(packets.first().unwrap().granule_position) as u32;
+
+    // Vorbis carries three header packets (identification, comments,
+    // setup) before the coded audio frames; Opus carries two (OpusHead,
+    // OpusTags). None of them contain audio data of their own.
+    let header_count = if format == BareOggFormat::Vorbis { 3 } else { 2 };
+    let vorbis_headers: Vec<&[u8]> = packets
+        .iter()
+        .take(header_count)
+        .map(|p| p.data.as_slice())
+        .collect();
+    let samples: Vec<&Packet> = packets.iter().skip(header_count).collect();
+
+    let mut out = Vec::new();
+    write_ftyp(&mut out);
+    write_moov(&mut out, 1, timescale, duration, format, codec_info, &vorbis_headers);
+
+    let owned_samples: Vec<Packet> = samples
+        .into_iter()
+        .map(|packet| Packet {
+            stream_serial: packet.stream_serial,
+            data: packet.data.clone(),
+            eos: packet.eos,
+            granule_position: packet.granule_position,
+        })
+        .collect();
+    write_fragment(&mut out, 1, 1, format, &owned_samples);
+
+    Some(out)
+}